@@ -1,16 +1,31 @@
 extern crate audrey;
+extern crate cpal;
 extern crate dasp_interpolate;
 extern crate dasp_signal;
 extern crate deepspeech;
+extern crate ebur128;
+extern crate nnnoiseless;
+extern crate num_complex;
+extern crate ouroboros;
+extern crate realfft;
 
-use std::env::args;
-use std::fs::File;
-use std::path::Path;
+use std::cell::{Cell, RefCell};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use audrey::read::Reader;
+use clap::Clap;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::Sample;
 use dasp_interpolate::linear::Linear;
 use dasp_signal::{from_iter, interpolate::Converter, Signal};
-use deepspeech::Model;
+use deepspeech::{Model, Stream as DeepspeechStream};
+use ebur128::{EbuR128, Mode as LoudnessMode};
+use nnnoiseless::DenoiseState;
+use ouroboros::self_referencing;
+use realfft::RealFftPlanner;
+use serde::{Deserialize, Serialize};
 
 use crate::config;
 use crate::window::ExampleApplicationWindow;
@@ -21,15 +36,613 @@ use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 use gtk::{gdk, gio, glib};
 use gtk_macros::action;
-use log::{debug, info};
+use log::{debug, info, warn};
 use once_cell::sync::OnceCell;
 
+// The model has been trained on this specific sample rate.
+const SAMPLE_RATE: u32 = 16_000;
+
+/// Owns the `Model` used for a live dictation session together with the
+/// `Stream` borrowed from it, since `deepspeech::Stream<'a>` ties its
+/// lifetime to the model it streams against and the model can't keep living
+/// in `ExampleApplication` while a stream borrows it. `stream` is `None`
+/// once `finish` has consumed it.
+#[self_referencing]
+struct ModelStream {
+    model: Box<Model>,
+    #[borrows(mut model)]
+    #[not_covariant]
+    stream: RefCell<Option<DeepspeechStream<'this>>>,
+}
+
+impl ModelStream {
+    fn for_model(model: Model) -> Self {
+        ModelStreamBuilder {
+            model: Box::new(model),
+            stream_builder: |model| RefCell::new(Some(model.create_stream().unwrap())),
+        }
+        .build()
+    }
+
+    /// Feeds a block of audio into the stream and returns the current
+    /// partial transcript.
+    fn feed(&self, audio: &[i16]) -> String {
+        self.with_stream(|stream| {
+            let mut stream = stream.borrow_mut();
+            let stream = stream.as_mut().expect("fed after finish");
+            stream.feed_audio(audio);
+            stream.intermediate_decode()
+        })
+    }
+
+    /// Consumes the stream to get its final transcript.
+    fn finish(&self) -> String {
+        self.with_stream(|stream| {
+            let stream = stream.borrow_mut().take().expect("finished twice");
+            stream.finish_stream().unwrap()
+        })
+    }
+
+    /// Reclaims the model once the session is done with it, so it can be
+    /// reused for the next recording.
+    fn into_model(self) -> Model {
+        *self.into_heads().model
+    }
+}
+
+/// Keeps the state needed to turn raw microphone callbacks into a running
+/// DeepSpeech transcript: the input stream itself (dropping it stops
+/// capture) and the model/stream pair partial transcripts are pulled from.
+struct LiveSession {
+    // Kept alive only so the cpal stream isn't dropped; never read.
+    _input_stream: cpal::Stream,
+    model_stream: Rc<ModelStream>,
+}
+
+/// Per-callback state threaded through [`build_capture_stream`], gathered
+/// into one struct so the generic function's signature doesn't balloon.
+struct CapturePipeline {
+    input_hz: f64,
+    carry: Rc<RefCell<Option<i16>>>,
+    normalizer: Rc<RefCell<Normalizer>>,
+    normalize_enabled: bool,
+    denoiser: Rc<RefCell<Denoiser>>,
+    denoise_enabled: bool,
+    model_stream: Rc<ModelStream>,
+    sender: glib::Sender<String>,
+}
+
+/// Builds the live-dictation input stream for any cpal sample format,
+/// converting each captured block to i16 before running it through the
+/// shared resample/normalize/denoise/feed pipeline. `default_input_config`
+/// commonly reports F32 or U16 rather than I16, so the format the device
+/// actually offers has to be handled rather than assumed.
+fn build_capture_stream<T: cpal::Sample>(
+    device: &cpal::Device,
+    stream_config: &cpal::StreamConfig,
+    pipeline: CapturePipeline,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let CapturePipeline {
+        input_hz,
+        carry,
+        normalizer,
+        normalize_enabled,
+        denoiser,
+        denoise_enabled,
+        model_stream,
+        sender,
+    } = pipeline;
+    let err_fn = |err| warn!("Input stream error: {}", err);
+    device.build_input_stream(
+        stream_config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let data: Vec<i16> = data.iter().map(|s| s.to_i16()).collect();
+            let resampled =
+                resample_block(&data, input_hz, SAMPLE_RATE as f64, &mut carry.borrow_mut());
+            if resampled.is_empty() {
+                return;
+            }
+            let resampled = if normalize_enabled {
+                normalizer.borrow_mut().process(&resampled)
+            } else {
+                resampled
+            };
+            let resampled = if denoise_enabled {
+                denoiser.borrow_mut().process(&resampled)
+            } else {
+                resampled
+            };
+            if resampled.is_empty() {
+                return;
+            }
+            let partial = model_stream.feed(&resampled);
+            let _ = sender.send(partial);
+        },
+        err_fn,
+    )
+}
+
+/// Incrementally resamples `block` (in `from_hz`) to `to_hz`, carrying the
+/// last input sample over between calls so the `Linear` interpolator
+/// doesn't lose its place at block boundaries.
+fn resample_block(block: &[i16], from_hz: f64, to_hz: f64, carry: &mut Option<i16>) -> Vec<i16> {
+    if from_hz as u32 == to_hz as u32 {
+        return block.to_vec();
+    }
+
+    let first = carry.unwrap_or_else(|| block.first().copied().unwrap_or(0));
+    let interpolator = Linear::new([first], [first]);
+    let conv = Converter::from_hz_to_hz(
+        from_iter(block.iter().map(|s| [*s])),
+        interpolator,
+        from_hz,
+        to_hz,
+    );
+    let resampled: Vec<i16> = conv.until_exhausted().map(|v| v[0]).collect();
+    // Leave `carry` untouched for an empty block rather than resetting it to
+    // `None`; otherwise a call that finds no samples (e.g. `Denoiser` between
+    // RNNoise frames) would silently discard the real carried-over sample and
+    // introduce a small discontinuity in the next block's interpolation.
+    if let Some(&last) = block.last() {
+        *carry = Some(last);
+    }
+    resampled
+}
+
+// Caps how far `Normalizer::process` will push a block's gain in either
+// direction. Early in a session the integrated loudness estimate is based on
+// very little audio and can read far quieter than the real signal (e.g.
+// near-silence before speech starts), which would otherwise call for gains
+// of 100x or more and audibly clip the input once real speech arrives.
+const MAX_GAIN_DB: f64 = 12.0;
+
+/// Measures integrated (EBU R128) loudness of a mono buffer as it arrives
+/// and rescales it towards `target_lufs`, clipping to `i16` range rather
+/// than overflowing. Run after resampling but before denoising, so RNNoise
+/// sees audio at a consistent level regardless of source gain.
+struct Normalizer {
+    state: EbuR128,
+    target_lufs: f64,
+}
+
+impl Normalizer {
+    fn new(sample_rate: u32, target_lufs: f64) -> Self {
+        Normalizer {
+            state: EbuR128::new(1, sample_rate, LoudnessMode::I).expect("invalid sample rate"),
+            target_lufs,
+        }
+    }
+
+    /// Feeds `block` into the running loudness measurement and returns it
+    /// scaled towards `target_lufs`. The gain is derived from loudness
+    /// measured so far, so it only stabilizes after the first couple
+    /// hundred ms of audio; it's capped to `MAX_GAIN_DB` in either direction
+    /// so an unreliable early estimate can't drive the signal into clipping.
+    fn process(&mut self, block: &[i16]) -> Vec<i16> {
+        self.state.add_frames_i16(block).unwrap();
+        let integrated = self.state.loudness_global().unwrap_or(self.target_lufs);
+        if !integrated.is_finite() {
+            return block.to_vec();
+        }
+
+        let gain_db = (self.target_lufs - integrated).clamp(-MAX_GAIN_DB, MAX_GAIN_DB);
+        let gain_linear = 10f64.powf(gain_db / 20.0);
+        block
+            .iter()
+            .map(|&sample| {
+                let scaled = sample as f64 * gain_linear;
+                scaled.clamp(i16::MIN as f64, i16::MAX as f64) as i16
+            })
+            .collect()
+    }
+}
+
+// RNNoise's native rate; it operates on 10 ms (480-sample) frames at 48 kHz.
+const DENOISE_SAMPLE_RATE: u32 = 48_000;
+
+/// Wraps an RNNoise `DenoiseState` together with the incremental resampler
+/// state needed to run it over 16 kHz audio, since RNNoise only operates on
+/// 48 kHz, 480-sample frames. Feed it blocks of any size via `process`; a
+/// short tail that doesn't fill a whole frame is buffered until next time.
+struct Denoiser {
+    state: Box<DenoiseState<'static>>,
+    up_carry: Option<i16>,
+    down_carry: Option<i16>,
+    pending: Vec<f32>,
+}
+
+impl Denoiser {
+    fn new() -> Self {
+        Denoiser {
+            state: DenoiseState::new(),
+            up_carry: None,
+            down_carry: None,
+            pending: Vec::with_capacity(DenoiseState::FRAME_SIZE),
+        }
+    }
+
+    /// Denoises `block` (16 kHz mono PCM) and returns the cleaned signal,
+    /// still at 16 kHz.
+    fn process(&mut self, block: &[i16]) -> Vec<i16> {
+        let upsampled = resample_block(
+            block,
+            SAMPLE_RATE as f64,
+            DENOISE_SAMPLE_RATE as f64,
+            &mut self.up_carry,
+        );
+        self.pending.extend(upsampled.iter().map(|s| *s as f32));
+
+        let mut cleaned: Vec<f32> = Vec::new();
+        while self.pending.len() >= DenoiseState::FRAME_SIZE {
+            let frame: Vec<f32> = self.pending.drain(..DenoiseState::FRAME_SIZE).collect();
+            let mut out = [0f32; DenoiseState::FRAME_SIZE];
+            self.state.process_frame(&mut out, &frame);
+            cleaned.extend_from_slice(&out);
+        }
+
+        let cleaned: Vec<i16> = cleaned.iter().map(|s| *s as i16).collect();
+        resample_block(
+            &cleaned,
+            DENOISE_SAMPLE_RATE as f64,
+            SAMPLE_RATE as f64,
+            &mut self.down_carry,
+        )
+    }
+}
+
+// 32 ms frames at 16 kHz, 50% overlap.
+const VAD_FRAME_SIZE: usize = 512;
+const VAD_HOP_SIZE: usize = VAD_FRAME_SIZE / 2;
+// Roughly the band human speech's fundamental and harmonics live in.
+const VAD_BAND_LOW_HZ: f32 = 300.0;
+const VAD_BAND_HIGH_HZ: f32 = 3400.0;
+
+/// Tunables for [`detect_speech_segments`].
+struct VadConfig {
+    /// A frame counts as speech once its band energy exceeds
+    /// `noise_floor * threshold`.
+    threshold: f32,
+    /// Segments shorter than this (in samples) are dropped entirely.
+    min_segment_samples: usize,
+    /// Frames of hangover kept on either side of a detected speech run so
+    /// brief pauses between words don't split it into several segments.
+    hangover_frames: usize,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        VadConfig {
+            threshold: 3.5,
+            min_segment_samples: SAMPLE_RATE as usize / 5, // 200 ms
+            hangover_frames: 4,
+        }
+    }
+}
+
+impl VadConfig {
+    /// Builds a `VadConfig` from the user-configurable `vad_threshold`/
+    /// `vad_min_segment_ms` fields, keeping `hangover_frames` at its default.
+    fn from_audio_config(audio: &AudioConfig) -> Self {
+        VadConfig {
+            threshold: audio.vad_threshold,
+            min_segment_samples: (audio.vad_min_segment_ms as usize * audio.sample_rate as usize) / 1000,
+            ..VadConfig::default()
+        }
+    }
+}
+
+/// Splits a 16 kHz mono buffer into `(start_sample, end_sample)` speech
+/// segments, trimming leading/trailing silence and separating distinct
+/// utterances so DeepSpeech isn't run over (and doesn't hallucinate on)
+/// dead air. Frame band-energy in the speech range is compared against an
+/// adaptive noise floor tracked as an EMA over non-speech frames.
+fn detect_speech_segments(samples: &[i16], config: &VadConfig) -> Vec<(usize, usize)> {
+    if samples.len() < VAD_FRAME_SIZE {
+        return vec![(0, samples.len())];
+    }
+
+    let mut planner = RealFftPlanner::<f32>::new();
+    let fft = planner.plan_fft_forward(VAD_FRAME_SIZE);
+    let mut scratch = fft.make_scratch_vec();
+    let mut spectrum = fft.make_output_vec();
+
+    let hann: Vec<f32> = (0..VAD_FRAME_SIZE)
+        .map(|i| {
+            0.5 * (1.0
+                - (2.0 * std::f32::consts::PI * i as f32 / (VAD_FRAME_SIZE as f32 - 1.0)).cos())
+        })
+        .collect();
+
+    let bin_hz = SAMPLE_RATE as f32 / VAD_FRAME_SIZE as f32;
+    let low_bin = (VAD_BAND_LOW_HZ / bin_hz).floor() as usize;
+    let high_bin = ((VAD_BAND_HIGH_HZ / bin_hz).ceil() as usize).min(spectrum.len() - 1);
+
+    // Band energy of every frame, computed up front so the floor can be
+    // seeded from more than a single frame below.
+    let mut frame_energies = Vec::new();
+    let mut pos = 0;
+    while pos + VAD_FRAME_SIZE <= samples.len() {
+        let mut windowed: Vec<f32> = samples[pos..pos + VAD_FRAME_SIZE]
+            .iter()
+            .zip(&hann)
+            .map(|(sample, w)| *sample as f32 * w)
+            .collect();
+        fft.process_with_scratch(&mut windowed, &mut spectrum, &mut scratch)
+            .unwrap();
+        frame_energies.push(spectrum[low_bin..=high_bin].iter().map(|c| c.norm_sqr()).sum::<f32>());
+        pos += VAD_HOP_SIZE;
+    }
+
+    // Seed the floor from the minimum energy over the first few frames
+    // rather than a fixed constant or a single frame; real PCM band energy
+    // is many orders of magnitude above a small constant (misclassifying
+    // leading silence as speech until the EMA caught up), and seeding from
+    // frame 0 alone means a clip that opens on speech at a roughly steady
+    // level can never exceed its own seed and gets classified as silence
+    // start to finish. Taking the minimum over a short lead-in window still
+    // lets genuine onset frames separate from it.
+    const NOISE_FLOOR_SEED_FRAMES: usize = 4;
+    let mut noise_floor = frame_energies[..frame_energies.len().min(NOISE_FLOOR_SEED_FRAMES)]
+        .iter()
+        .cloned()
+        .fold(f32::INFINITY, f32::min)
+        .max(1.0);
+
+    let mut frame_is_speech = Vec::with_capacity(frame_energies.len());
+    for &band_energy in &frame_energies {
+        let is_speech = band_energy > noise_floor * config.threshold;
+        if !is_speech {
+            noise_floor = 0.95 * noise_floor + 0.05 * band_energy.max(1.0);
+        }
+        frame_is_speech.push(is_speech);
+    }
+
+    // Extend each speech run by `hangover_frames` on both sides.
+    let mut with_hangover = frame_is_speech.clone();
+    for (i, &speech) in frame_is_speech.iter().enumerate() {
+        if speech {
+            let start = i.saturating_sub(config.hangover_frames);
+            let end = (i + config.hangover_frames).min(frame_is_speech.len() - 1);
+            with_hangover[start..=end].iter_mut().for_each(|s| *s = true);
+        }
+    }
+
+    // Turn the per-frame mask into sample ranges, dropping short runs.
+    let mut segments = Vec::new();
+    let mut run_start: Option<usize> = None;
+    for (i, &speech) in with_hangover.iter().enumerate() {
+        let frame_start = i * VAD_HOP_SIZE;
+        if speech {
+            run_start.get_or_insert(frame_start);
+        } else if let Some(start) = run_start.take() {
+            let end = (frame_start + VAD_FRAME_SIZE).min(samples.len());
+            if end - start >= config.min_segment_samples {
+                segments.push((start, end));
+            }
+        }
+    }
+    if let Some(start) = run_start {
+        let end = samples.len();
+        if end - start >= config.min_segment_samples {
+            segments.push((start, end));
+        }
+    }
+
+    segments
+}
+
+/// How a multichannel file is turned into the mono signal DeepSpeech
+/// expects.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ChannelMode {
+    /// Average all channels together.
+    Downmix,
+    /// Keep only the given zero-indexed channel, discarding the rest.
+    Channel(usize),
+}
+
+impl Default for ChannelMode {
+    fn default() -> Self {
+        ChannelMode::Downmix
+    }
+}
+
+/// Collapses an interleaved multichannel buffer down to mono per `mode`,
+/// walking frames of `channel_count` interleaved samples at a time.
+fn downmix(samples: &[i16], channel_count: usize, mode: ChannelMode) -> Vec<i16> {
+    if channel_count <= 1 {
+        return samples.to_vec();
+    }
+
+    samples
+        .chunks(channel_count)
+        .map(|frame| match mode {
+            ChannelMode::Downmix => {
+                let sum: i64 = frame.iter().map(|&s| s as i64).sum();
+                (sum / frame.len() as i64) as i16
+            }
+            ChannelMode::Channel(index) => frame.get(index).copied().unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Persisted, per-user settings for the model, input device and audio
+/// pipeline toggles. Loaded from a TOML file at the XDG config path (see
+/// `Config::load`); the GTK window persists changes back with
+/// `Config::save`, and CLI flags (see `Cli`) override individual fields for
+/// a single run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    pub(crate) audio: AudioConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct AudioConfig {
+    /// Directory to search for a graph/scorer in if `graph_path`/
+    /// `scorer_path` aren't set explicitly.
+    model_dir: Option<PathBuf>,
+    /// Explicit path to the acoustic model graph, bypassing the directory
+    /// search in `model_dir`.
+    graph_path: Option<PathBuf>,
+    /// Explicit path to an external scorer.
+    scorer_path: Option<PathBuf>,
+    /// Target sample rate; DeepSpeech was trained on 16 kHz.
+    sample_rate: u32,
+    /// cpal input device name to record from; `None` means the host
+    /// default.
+    input_device: Option<String>,
+    normalize: bool,
+    /// Target integrated loudness, in LUFS, that `normalize` aims for.
+    target_lufs: f64,
+    denoise: bool,
+    vad: bool,
+    /// See [`VadConfig::threshold`].
+    vad_threshold: f32,
+    /// See [`VadConfig::min_segment_samples`], in milliseconds rather than
+    /// samples so it doesn't need updating if `sample_rate` changes.
+    vad_min_segment_ms: u64,
+    channel_mode: ChannelMode,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        let vad_defaults = VadConfig::default();
+        AudioConfig {
+            model_dir: None,
+            graph_path: None,
+            scorer_path: None,
+            sample_rate: SAMPLE_RATE,
+            input_device: None,
+            normalize: true,
+            target_lufs: -23.0,
+            denoise: true,
+            vad: true,
+            vad_threshold: vad_defaults.threshold,
+            vad_min_segment_ms: (vad_defaults.min_segment_samples as u64 * 1000) / SAMPLE_RATE as u64,
+            channel_mode: ChannelMode::Downmix,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            audio: AudioConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config from the XDG config path, falling back to defaults
+    /// if it doesn't exist yet or fails to parse.
+    fn load() -> Self {
+        match fs::read_to_string(Self::path()) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|err| {
+                warn!("Failed to parse config, using defaults: {}", err);
+                Config::default()
+            }),
+            Err(_) => Config::default(),
+        }
+    }
+
+    /// Writes the config back to the XDG config path, creating parent
+    /// directories as needed.
+    fn save(&self) -> std::io::Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = toml::to_string_pretty(self).expect("Config is always serializable");
+        fs::write(path, contents)
+    }
+
+    fn path() -> PathBuf {
+        glib::user_config_dir()
+            .join(config::APP_ID)
+            .join("config.toml")
+    }
+}
+
+/// CLI overrides layered on top of the TOML config; anything left unset
+/// here (`None`/`false`) falls back to the config file's value.
+#[derive(Clap, Debug)]
+#[clap(name = "handy-deepspeech", version = config::VERSION)]
+struct Cli {
+    /// Directory to search for a graph/scorer
+    #[clap(long)]
+    model_dir: Option<PathBuf>,
+    /// Audio file to run one-shot STT on; omit to use live dictation
+    audio_file: Option<PathBuf>,
+    /// Disable the RNNoise denoising stage for this run
+    #[clap(long)]
+    no_denoise: bool,
+    /// Disable VAD segmentation for this run
+    #[clap(long)]
+    no_vad: bool,
+    /// Disable loudness normalization for this run
+    #[clap(long)]
+    no_normalize: bool,
+    /// Override the VAD speech/noise-floor threshold multiplier for this run
+    #[clap(long)]
+    vad_threshold: Option<f32>,
+    /// Override the VAD minimum segment length, in milliseconds, for this run
+    #[clap(long)]
+    vad_min_segment_ms: Option<u64>,
+}
+
+impl AudioConfig {
+    /// Applies CLI overrides on top of the loaded config.
+    fn apply_cli(&mut self, cli: &Cli) {
+        if cli.model_dir.is_some() {
+            self.model_dir = cli.model_dir.clone();
+        }
+        if cli.no_denoise {
+            self.denoise = false;
+        }
+        if cli.no_vad {
+            self.vad = false;
+        }
+        if cli.no_normalize {
+            self.normalize = false;
+        }
+        if let Some(threshold) = cli.vad_threshold {
+            self.vad_threshold = threshold;
+        }
+        if let Some(min_segment_ms) = cli.vad_min_segment_ms {
+            self.vad_min_segment_ms = min_segment_ms;
+        }
+    }
+}
+
 mod imp {
     use super::*;
 
-    #[derive(Debug, Default)]
+    #[derive(Default)]
     pub struct ExampleApplication {
         pub window: OnceCell<WeakRef<ExampleApplicationWindow>>,
+        pub model: RefCell<Option<Model>>,
+        pub live_session: RefCell<Option<LiveSession>>,
+        // Whether captured/loaded audio is run through RNNoise before STT.
+        pub denoise_enabled: Cell<bool>,
+        // How multichannel input is collapsed to the mono signal STT needs.
+        pub channel_mode: Cell<ChannelMode>,
+        // Whether captured/loaded audio is loudness-normalized before STT.
+        pub normalize_enabled: Cell<bool>,
+        // Target integrated loudness, in LUFS, `normalize_enabled` aims for.
+        pub target_lufs: Cell<f64>,
+        // cpal input device name to record from; `None` means the host default.
+        pub input_device: RefCell<Option<String>>,
+    }
+
+    impl std::fmt::Debug for ExampleApplication {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("ExampleApplication")
+                .field("window", &self.window)
+                .finish()
+        }
     }
 
     #[glib::object_subclass]
@@ -61,8 +674,13 @@ mod imp {
                 .set(window.downgrade())
                 .expect("Window already set.");
 
+            priv_.denoise_enabled.set(true);
+            priv_.normalize_enabled.set(true);
+            priv_.target_lufs.set(-23.0);
+
             app.setup_gactions();
             app.setup_accels();
+            app.load_model_for_dictation();
 
             app.get_main_window().present();
         }
@@ -116,11 +734,64 @@ impl ExampleApplication {
                 app.show_about_dialog();
             })
         );
+
+        // Start/stop live dictation
+        action!(
+            self,
+            "record",
+            clone!(@weak self as app => move |_, _| {
+                app.start_recording();
+            })
+        );
+        action!(
+            self,
+            "stop-recording",
+            clone!(@weak self as app => move |_, _| {
+                app.stop_recording();
+            })
+        );
+
+        // Toggle RNNoise denoising on/off; reflected in the window's
+        // denoise switch.
+        let denoise_action = gio::SimpleAction::new_stateful(
+            "denoise",
+            None,
+            &glib::Variant::from(true),
+        );
+        denoise_action.connect_activate(clone!(@weak self as app => move |action, _| {
+            let priv_ = imp::ExampleApplication::from_instance(&app);
+            let enabled = !priv_.denoise_enabled.get();
+            priv_.denoise_enabled.set(enabled);
+            action.set_state(&glib::Variant::from(enabled));
+            app.save_config();
+        }));
+        self.add_action(&denoise_action);
+
+        // Select which channel of a multichannel file/device to use, or
+        // -1 to average all of them together. Mirrors the window's channel
+        // picker.
+        let channel_action =
+            gio::SimpleAction::new_stateful("select-channel", Some(&i32::static_variant_type()), &glib::Variant::from(-1i32));
+        channel_action.connect_activate(clone!(@weak self as app => move |action, parameter| {
+            let priv_ = imp::ExampleApplication::from_instance(&app);
+            let requested = parameter.and_then(|v| v.get::<i32>()).unwrap_or(-1);
+            let mode = if requested < 0 {
+                ChannelMode::Downmix
+            } else {
+                ChannelMode::Channel(requested as usize)
+            };
+            priv_.channel_mode.set(mode);
+            action.set_state(&glib::Variant::from(requested));
+            app.save_config();
+        }));
+        self.add_action(&channel_action);
     }
 
     // Sets up keyboard shortcuts
     fn setup_accels(&self) {
         self.set_accels_for_action("app.quit", &["<primary>q"]);
+        self.set_accels_for_action("app.record", &["<primary>r"]);
+        self.set_accels_for_action("app.stop-recording", &["<primary>t"]);
         self.set_accels_for_action("win.show-help-overlay", &["<primary>question"]);
     }
 
@@ -158,48 +829,254 @@ impl ExampleApplication {
         info!("Version: {} ({})", config::VERSION, config::PROFILE);
         info!("Datadir: {}", config::PKGDATADIR);
 
-        self.main;
+        // `main` only handles the one-shot CLI file-transcription path; when
+        // no audio file argument was given we fall straight through to the
+        // interactive GTK app below.
+        if Cli::parse().audio_file.is_some() {
+            Self::main();
+        }
 
         ApplicationExtManual::run(self);
     }
 
-    // The model has been trained on this specific
-    // sample rate.
+    /// Loads the model used for live dictation from the configured model dir
+    /// (config file, overridden by `--model-dir`), picking the graph the same
+    /// way `main` does for one-shot transcription. A missing model dir or a
+    /// model that fails to load leaves no model loaded, so `start_recording`
+    /// declines to start rather than the whole app crashing; the "record"
+    /// action itself stays enabled.
+    fn load_model_for_dictation(&self) {
+        let priv_ = imp::ExampleApplication::from_instance(self);
+        let mut config = Config::load();
+        config.audio.apply_cli(&Cli::parse());
+
+        let model_dir = match config.audio.model_dir {
+            Some(dir) => dir,
+            None => {
+                debug!("No model dir configured, live dictation is disabled");
+                return;
+            }
+        };
+        priv_.denoise_enabled.set(config.audio.denoise);
+        priv_.channel_mode.set(config.audio.channel_mode);
+        priv_.normalize_enabled.set(config.audio.normalize);
+        priv_.target_lufs.set(config.audio.target_lufs);
+        priv_.input_device.replace(config.audio.input_device);
+
+        let graph_path = config.audio.graph_path.clone().unwrap_or_else(|| {
+            model_dir
+                .read_dir()
+                .into_iter()
+                .flatten()
+                .filter_map(Result::ok)
+                .map(|f| f.path())
+                .filter(|p| {
+                    p.is_file()
+                        && p.extension().map_or(false, |ext| ext == "pb" || ext == "pbmm" || ext == "tflite")
+                })
+                .last()
+                .unwrap_or_else(|| model_dir.join("output_graph.pb"))
+        });
+        match Model::load_from_files(&graph_path) {
+            Ok(model) => priv_.model.replace(Some(model)),
+            Err(err) => {
+                warn!("Failed to load model for live dictation: {:?}", err);
+                return;
+            }
+        };
+    }
+
+    /// Persists the current denoise/channel/normalize settings back to the
+    /// TOML config, on top of whatever else is already saved there.
+    fn save_config(&self) {
+        let priv_ = imp::ExampleApplication::from_instance(self);
+        let mut config = Config::load();
+        config.audio.denoise = priv_.denoise_enabled.get();
+        config.audio.channel_mode = priv_.channel_mode.get();
+        config.audio.normalize = priv_.normalize_enabled.get();
+        config.audio.target_lufs = priv_.target_lufs.get();
+        if let Err(err) = config.save() {
+            warn!("Failed to save config: {}", err);
+        }
+    }
+
+    /// Opens the configured input device (or the host default, if none is
+    /// configured) and starts streaming recognition, feeding DeepSpeech as
+    /// audio arrives and pushing partial transcripts into the main window so
+    /// the user sees them while still talking.
+    fn start_recording(&self) {
+        let priv_ = imp::ExampleApplication::from_instance(self);
+
+        if priv_.live_session.borrow().is_some() {
+            debug!("Recording already in progress");
+            return;
+        }
+
+        if priv_.model.borrow().is_none() {
+            warn!("No model loaded yet, can't start live dictation");
+            return;
+        }
 
-    /*
-    TODO list:
-    * better resampling (right now it seems that recognition is impaired compared to manual resampling)...
-    maybe use sinc?
-    * channel cropping
-    * use clap or something to parse the command line arguments
-    */
+        let host = cpal::default_host();
+        let wanted_device = priv_.input_device.borrow().clone();
+        let device = match &wanted_device {
+            Some(name) => host.input_devices().ok().and_then(|mut devices| {
+                devices.find(|d| d.name().ok().as_deref() == Some(name.as_str()))
+            }),
+            None => host.default_input_device(),
+        };
+        let device = match device {
+            Some(device) => device,
+            None => {
+                match &wanted_device {
+                    Some(name) => warn!("Configured input device \"{}\" not found", name),
+                    None => warn!("No default input device available"),
+                }
+                return;
+            }
+        };
+        let config = match device.default_input_config() {
+            Ok(config) => config,
+            Err(err) => {
+                warn!("Failed to query default input config: {}", err);
+                return;
+            }
+        };
+        let input_hz = config.sample_rate().0 as f64;
+
+        let model = priv_.model.borrow_mut().take().unwrap();
+        let model_stream = Rc::new(ModelStream::for_model(model));
+
+        let (sender, receiver) =
+            glib::MainContext::channel::<String>(glib::PRIORITY_DEFAULT);
+        let window_weak = priv_.window.get().unwrap().clone();
+        receiver.attach(None, move |partial| {
+            if let Some(window) = window_weak.upgrade() {
+                window.show_partial_transcript(&partial);
+            }
+            glib::Continue(true)
+        });
+
+        let carry = Rc::new(RefCell::new(None));
+        let normalizer = Rc::new(RefCell::new(Normalizer::new(
+            SAMPLE_RATE,
+            priv_.target_lufs.get(),
+        )));
+        let normalize_enabled = priv_.normalize_enabled.get();
+        let denoiser = Rc::new(RefCell::new(Denoiser::new()));
+        let denoise_enabled = priv_.denoise_enabled.get();
+        let stream_for_cb = model_stream.clone();
+        let sample_format = config.sample_format();
+        let stream_config = config.into();
+        let pipeline = CapturePipeline {
+            input_hz,
+            carry,
+            normalizer,
+            normalize_enabled,
+            denoiser,
+            denoise_enabled,
+            model_stream: stream_for_cb,
+            sender,
+        };
+
+        // `default_input_config` commonly reports F32 (or U16), not I16;
+        // build against whatever format the device actually offers rather
+        // than assuming it's i16.
+        let input_stream = match sample_format {
+            cpal::SampleFormat::I16 => build_capture_stream::<i16>(&device, &stream_config, pipeline),
+            cpal::SampleFormat::U16 => build_capture_stream::<u16>(&device, &stream_config, pipeline),
+            cpal::SampleFormat::F32 => build_capture_stream::<f32>(&device, &stream_config, pipeline),
+        }
+        .unwrap();
+        input_stream.play().unwrap();
+
+        priv_.live_session.replace(Some(LiveSession {
+            _input_stream: input_stream,
+            model_stream,
+        }));
+    }
+
+    /// Stops capture, finalizes the DeepSpeech stream and shows the final
+    /// transcript.
+    fn stop_recording(&self) {
+        let priv_ = imp::ExampleApplication::from_instance(self);
+        let session = match priv_.live_session.borrow_mut().take() {
+            Some(session) => session,
+            None => {
+                debug!("Not currently recording");
+                return;
+            }
+        };
+
+        // Dropping `_input_stream` stops capture; `model_stream` is still
+        // wrapped in an `Rc` held by the (now dead) audio callback, so this
+        // is the last strong reference and `try_unwrap` is expected to
+        // succeed.
+        drop(session._input_stream);
+        match Rc::try_unwrap(session.model_stream) {
+            Ok(model_stream) => {
+                let result = model_stream.finish();
+                priv_.model.replace(Some(model_stream.into_model()));
+                self.get_main_window().show_partial_transcript(&result);
+            }
+            Err(_) => warn!("DeepSpeech stream still in use, couldn't finalize"),
+        }
+    }
+
+    /// One-shot file transcription: load the TOML config, let CLI flags
+    /// override it, then run the model/denoise/VAD pipeline over the
+    /// requested file. Replaces the old `args().nth(1)`/`args().nth(2)`
+    /// positional parsing.
     fn main() {
-        const SAMPLE_RATE: u32 = 16_000;
-        let model_dir_str = args().nth(1).expect("Please specify model dir");
-        let audio_file_path = args()
-            .nth(2)
+        let cli = Cli::parse();
+        let mut config = Config::load();
+        config.audio.apply_cli(&cli);
+        let audio = &config.audio;
+
+        let model_dir_str = audio
+            .model_dir
+            .clone()
+            .expect("No model dir set; pass --model-dir or add one to the config file");
+        let audio_file_path = cli
+            .audio_file
             .expect("Please specify an audio file to run STT on");
-        let dir_path = Path::new(&model_dir_str);
-        let mut graph_name: Box<Path> = dir_path.join("output_graph.pb").into_boxed_path();
-        let mut scorer_name: Option<Box<Path>> = None;
-        // search for model in model directory
-        for file in dir_path
-            .read_dir()
-            .expect("Specified model dir is not a dir")
-        {
-            if let Ok(f) = file {
-                let file_path = f.path();
-                if file_path.is_file() {
-                    if let Some(ext) = file_path.extension() {
-                        if ext == "pb" || ext == "pbmm" || ext == "tflite" {
-                            graph_name = file_path.into_boxed_path();
-                        } else if ext == "scorer" {
-                            scorer_name = Some(file_path.into_boxed_path());
+
+        let graph_name: Box<Path> = match &audio.graph_path {
+            Some(path) => path.clone().into_boxed_path(),
+            None => {
+                let dir_path = Path::new(&model_dir_str);
+                let mut graph_name: Box<Path> = dir_path.join("output_graph.pb").into_boxed_path();
+                // search for model in model directory
+                for file in dir_path
+                    .read_dir()
+                    .expect("Specified model dir is not a dir")
+                {
+                    if let Ok(f) = file {
+                        let file_path = f.path();
+                        if file_path.is_file() {
+                            if let Some(ext) = file_path.extension() {
+                                if ext == "pb" || ext == "pbmm" || ext == "tflite" {
+                                    graph_name = file_path.into_boxed_path();
+                                }
+                            }
                         }
                     }
                 }
+                graph_name
             }
-        }
+        };
+        let scorer_name: Option<Box<Path>> = audio.scorer_path.clone().map(|p| p.into_boxed_path()).or_else(|| {
+            let dir_path = Path::new(&model_dir_str);
+            dir_path.read_dir().ok()?.filter_map(Result::ok).find_map(|f| {
+                let file_path = f.path();
+                if file_path.is_file() && file_path.extension().map_or(false, |ext| ext == "scorer") {
+                    Some(file_path.into_boxed_path())
+                } else {
+                    None
+                }
+            })
+        });
+
         let mut m = Model::load_from_files(&graph_name).unwrap();
         // enable external scorer if found in the model folder
         if let Some(scorer) = scorer_name {
@@ -210,29 +1087,55 @@ impl ExampleApplication {
         let audio_file = File::open(audio_file_path).unwrap();
         let mut reader = Reader::new(audio_file).unwrap();
         let desc = reader.description();
-        assert_eq!(
-            1,
-            desc.channel_count(),
-            "The channel count is required to be one, at least for now"
-        );
+        let channel_count = desc.channel_count() as usize;
+
+        // Read the interleaved samples and collapse them down to mono,
+        // averaging channels by default (see `ChannelMode`).
+        let interleaved: Vec<i16> = reader.samples().map(|s| s.unwrap()).collect();
+        let mono = downmix(&interleaved, channel_count, audio.channel_mode);
 
         // Obtain the buffer of samples
-        let audio_buf: Vec<_> = if desc.sample_rate() == SAMPLE_RATE {
-            reader.samples().map(|s| s.unwrap()).collect()
+        let audio_buf: Vec<_> = if desc.sample_rate() == audio.sample_rate {
+            mono
         } else {
             // We need to interpolate to the target sample rate
             let interpolator = Linear::new([0i16], [0]);
             let conv = Converter::from_hz_to_hz(
-                from_iter(reader.samples::<i16>().map(|s| [s.unwrap()])),
+                from_iter(mono.iter().map(|s| [*s])),
                 interpolator,
                 desc.sample_rate() as f64,
-                SAMPLE_RATE as f64,
+                audio.sample_rate as f64,
             );
             conv.until_exhausted().map(|v| v[0]).collect()
         };
 
-        // Run the speech to text algorithm
-        let result = m.speech_to_text(&audio_buf).unwrap();
+        // Normalize loudness before denoising, so RNNoise sees audio at a
+        // consistent level regardless of source gain.
+        let audio_buf = if audio.normalize {
+            Normalizer::new(audio.sample_rate, audio.target_lufs).process(&audio_buf)
+        } else {
+            audio_buf
+        };
+
+        let audio_buf = if audio.denoise {
+            let mut denoiser = Denoiser::new();
+            denoiser.process(&audio_buf)
+        } else {
+            audio_buf
+        };
+
+        // Trim silence and split into utterances so each is transcribed
+        // independently, then stitch the results back together.
+        let result = if audio.vad {
+            let segments = detect_speech_segments(&audio_buf, &VadConfig::from_audio_config(audio));
+            segments
+                .into_iter()
+                .map(|(start, end)| m.speech_to_text(&audio_buf[start..end]).unwrap())
+                .collect::<Vec<_>>()
+                .join(" ")
+        } else {
+            m.speech_to_text(&audio_buf).unwrap()
+        };
 
         // Output the result
         println!("{}", result);